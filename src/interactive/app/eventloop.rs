@@ -1,7 +1,7 @@
 use crate::interactive::{
     react::Terminal,
     sorted_entries,
-    widgets::{ReactHelpPane, ReactMainWindow},
+    widgets::{ReactHelpPane, ReactMainWindow, ReactMarkPane},
     ByteVisualization, DisplayOptions, EntryDataBundle, SortMode,
 };
 use dua::{
@@ -11,15 +11,21 @@ use dua::{
 };
 use failure::Error;
 use itertools::Itertools;
-use petgraph::Direction;
-use std::{io, path::PathBuf};
-use termion::input::{Keys, TermReadEventsAndRaw};
+use petgraph::{visit::Dfs, Direction};
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+};
+use termion::{async_stdin, input::TermReadEventsAndRaw};
 use tui::backend::Backend;
 
 #[derive(Copy, Clone)]
 pub enum FocussedPane {
     Main,
     Help,
+    Mark,
+    Search,
 }
 
 impl Default for FocussedPane {
@@ -28,6 +34,20 @@ impl Default for FocussedPane {
     }
 }
 
+/// Tracks which entries the user has marked for deletion.
+#[derive(Default)]
+pub struct MarkMode {
+    pub marked: BTreeSet<TreeIndex>,
+}
+
+impl MarkMode {
+    fn toggle(&mut self, index: TreeIndex) {
+        if !self.marked.remove(&index) {
+            self.marked.insert(index);
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct AppState {
     pub root: TreeIndex,
@@ -36,6 +56,26 @@ pub struct AppState {
     pub sorting: SortMode,
     pub message: Option<String>,
     pub focussed: FocussedPane,
+    pub marked: MarkMode,
+    pub query: Option<String>,
+    pending_g: bool,
+}
+
+impl AppState {
+    /// The entries of the current directory, restricted to those whose name
+    /// contains the active search query (case-insensitively), if any.
+    pub fn visible_entries(&self) -> Vec<&EntryDataBundle> {
+        match self.query {
+            Some(ref query) if !query.is_empty() => {
+                let query = query.to_lowercase();
+                self.entries
+                    .iter()
+                    .filter(|e| e.name.to_string_lossy().to_lowercase().contains(&query))
+                    .collect()
+            }
+            _ => self.entries.iter().collect(),
+        }
+    }
 }
 
 /// State and methods representing the interactive disk usage analyser for the terminal
@@ -44,6 +84,7 @@ pub struct TerminalApp {
     pub display: DisplayOptions,
     pub state: AppState,
     pub window: ReactMainWindow,
+    pub walk_options: WalkOptions,
 }
 
 enum CursorDirection {
@@ -51,6 +92,8 @@ enum CursorDirection {
     Down,
     Up,
     PageUp,
+    Top,
+    Bottom,
 }
 
 impl TerminalApp {
@@ -66,32 +109,64 @@ impl TerminalApp {
     pub fn process_events<B, R>(
         &mut self,
         terminal: &mut Terminal<B>,
-        keys: Keys<R>,
+        input: R,
     ) -> Result<WalkResult, Error>
     where
         B: Backend,
         R: io::Read + TermReadEventsAndRaw,
     {
-        use termion::event::Key::{Char, Ctrl};
+        use termion::event::Key::{Backspace, Char, Ctrl, Esc};
+        use termion::event::{Event, MouseButton, MouseEvent};
         use FocussedPane::*;
 
         self.draw(terminal)?;
-        for key in keys.filter_map(Result::ok) {
+        for (event, _raw) in input.events_and_raw().filter_map(Result::ok) {
             self.update_message();
-            match key {
-                Char('?') => self.toggle_help_pane(),
-                Char('\t') => {
-                    self.cycle_focus();
+            let key = match event {
+                Event::Key(key) => key,
+                Event::Mouse(MouseEvent::Press(MouseButton::WheelUp, ..)) => {
+                    self.change_entry_selection(CursorDirection::Up);
+                    self.draw(terminal)?;
+                    continue;
+                }
+                Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, ..)) => {
+                    self.change_entry_selection(CursorDirection::Down);
+                    self.draw(terminal)?;
+                    continue;
                 }
-                Ctrl('c') => break,
-                Char('q') => match self.state.focussed {
-                    Main => break,
-                    Help => {
-                        self.state.focussed = Main;
-                        self.window.help_pane = None
+                _ => continue,
+            };
+            if key != Char('g') && self.state.pending_g {
+                // A lone `g` that never became `gg` falls back to its original
+                // meaning (cycling the byte visualization) instead of silently
+                // doing nothing.
+                self.display.byte_vis.cycle();
+                self.state.pending_g = false;
+            }
+            if let Ctrl('c') = key {
+                break;
+            }
+            // While typing a search query, every other key is plain input rather than a shortcut.
+            if !matches!(self.state.focussed, Search) {
+                match key {
+                    Char('?') => self.toggle_help_pane(),
+                    Char('\t') => {
+                        self.cycle_focus();
                     }
-                },
-                _ => {}
+                    Char('q') => match self.state.focussed {
+                        Main => break,
+                        Mark => {
+                            self.state.focussed = Main;
+                            self.window.mark_pane = None
+                        }
+                        Help => {
+                            self.state.focussed = Main;
+                            self.window.help_pane = None
+                        }
+                        Search => unreachable!(),
+                    },
+                    _ => {}
+                }
             }
 
             match self.state.focussed {
@@ -102,6 +177,13 @@ impl TerminalApp {
                     Ctrl('d') => self.scroll_help(CursorDirection::PageDown),
                     _ => {}
                 },
+                FocussedPane::Mark => match key {
+                    Char('d') => self.delete_marked_entries(),
+                    Char(' ') => self.toggle_mark_for_entry(),
+                    Char('k') => self.change_entry_selection(CursorDirection::Up),
+                    Char('j') => self.change_entry_selection(CursorDirection::Down),
+                    _ => {}
+                },
                 FocussedPane::Main => match key {
                     Char('O') => self.open_that(),
                     Char('u') => self.exit_node(),
@@ -110,8 +192,52 @@ impl TerminalApp {
                     Char('k') => self.change_entry_selection(CursorDirection::Up),
                     Char('j') => self.change_entry_selection(CursorDirection::Down),
                     Ctrl('d') => self.change_entry_selection(CursorDirection::PageDown),
-                    Char('s') => self.state.sorting.toggle_size(),
-                    Char('g') => self.display.byte_vis.cycle(),
+                    Char('s') => {
+                        self.state.sorting.toggle_size();
+                        self.refresh_entries();
+                    }
+                    Char('c') => {
+                        self.state.sorting.toggle_entry_count();
+                        self.refresh_entries();
+                    }
+                    Char('m') => {
+                        self.state.sorting.toggle_mtime();
+                        self.refresh_entries();
+                    }
+                    Char('n') => {
+                        self.state.sorting.toggle_name();
+                        self.refresh_entries();
+                    }
+                    Char('g') => {
+                        if self.state.pending_g {
+                            self.change_entry_selection(CursorDirection::Top);
+                            self.state.pending_g = false;
+                        } else {
+                            self.state.pending_g = true;
+                        }
+                    }
+                    Char('G') => self.change_entry_selection(CursorDirection::Bottom),
+                    Char('R') => {
+                        if let Err(err) = self.rescan(terminal) {
+                            self.state.message = Some(err.to_string());
+                        }
+                    }
+                    Char(' ') => self.toggle_mark_for_entry(),
+                    Char('/') => self.state.focussed = FocussedPane::Search,
+                    _ => {}
+                },
+                FocussedPane::Search => match key {
+                    Char('\n') => self.state.focussed = FocussedPane::Main,
+                    Esc => {
+                        self.state.query = None;
+                        self.state.focussed = FocussedPane::Main;
+                    }
+                    Backspace => {
+                        if let Some(ref mut query) = self.state.query {
+                            query.pop();
+                        }
+                    }
+                    Char(c) => self.state.query.get_or_insert_with(String::new).push(c),
                     _ => {}
                 },
             };
@@ -126,15 +252,20 @@ impl TerminalApp {
         use FocussedPane::*;
         self.state.focussed = match (self.state.focussed, &self.window.help_pane) {
             (Main, Some(_)) => Help,
+            (Help, _) if !self.state.marked.marked.is_empty() => Mark,
             (Help, _) => Main,
+            (Mark, _) => Main,
             _ => Main,
         };
+        if let (Mark, true) = (self.state.focussed, self.window.mark_pane.is_none()) {
+            self.window.mark_pane = Some(ReactMarkPane::default());
+        }
     }
 
     fn toggle_help_pane(&mut self) {
         use FocussedPane::*;
         self.state.focussed = match self.state.focussed {
-            Main => {
+            Main | Mark | Search => {
                 self.window.help_pane = Some(ReactHelpPane::default());
                 Help
             }
@@ -158,6 +289,279 @@ impl TerminalApp {
         }
     }
 
+    /// Toggles whether the currently selected entry is marked for deletion, and
+    /// while any entry is marked, switches focus to the mark pane so the user can
+    /// review and confirm.
+    fn toggle_mark_for_entry(&mut self) {
+        if let Some(idx) = self.state.selected {
+            self.state.marked.toggle(idx);
+            if !self.state.marked.marked.is_empty() {
+                self.window.mark_pane = Some(ReactMarkPane::default());
+                self.state.focussed = FocussedPane::Mark;
+            }
+        }
+    }
+
+    /// Removes the marked entries from disk, accumulates errors, and prunes the
+    /// deleted nodes (along with their byte totals) from the traversal tree.
+    /// A marked current root or ancestor of it is skipped, not deleted.
+    fn delete_marked_entries(&mut self) {
+        let marked_paths: Vec<PathBuf> = self
+            .state
+            .marked
+            .marked
+            .drain()
+            .map(|idx| path_of(&self.traversal.tree, idx))
+            .collect();
+
+        let root_path = path_of(&self.traversal.tree, self.state.root);
+        let selected_path = self
+            .state
+            .selected
+            .map(|idx| path_of(&self.traversal.tree, idx));
+
+        // A directory and something inside it can both be marked at once; deleting the
+        // directory already takes care of its contents, so drop anything whose ancestor
+        // is also marked rather than re-resolving a path that's about to disappear.
+        // Also refuse to delete the current root or any of its ancestors: pruning that
+        // subtree would leave `state.root` pointing at a removed node with nothing
+        // sane to re-resolve to.
+        let mut blocked_root_deletion = false;
+        let to_delete: Vec<PathBuf> = marked_paths
+            .iter()
+            .filter(|path| {
+                !marked_paths
+                    .iter()
+                    .any(|other| {
+                        *path != other && path.starts_with(other) && !root_path.starts_with(other)
+                    })
+            })
+            .filter(|path| {
+                if root_path.starts_with(path) {
+                    blocked_root_deletion = true;
+                    false
+                } else {
+                    true
+                }
+            })
+            .cloned()
+            .collect();
+
+        for path in to_delete {
+            // Re-resolve right before acting on it: an earlier deletion in this same
+            // batch may have changed which `TreeIndex` this path lives at.
+            let idx = match self.resolve_index_by_path(&path) {
+                Some(idx) => idx,
+                None => continue,
+            };
+            let result = if path.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            match result {
+                Ok(()) => self.prune_entry(idx),
+                Err(err) => {
+                    self.traversal.io_errors += 1;
+                    self.state.message = Some(format!("{}: {}", path.display(), err));
+                }
+            }
+        }
+
+        if blocked_root_deletion {
+            self.state.message = Some(
+                "Skipped: can't delete the current directory or one of its ancestors".into(),
+            );
+        }
+
+        self.state.root = self
+            .resolve_index_by_path(&root_path)
+            .unwrap_or(self.state.root);
+        self.state.entries =
+            sorted_entries(&self.traversal.tree, self.state.root, self.state.sorting);
+        self.state.selected = selected_path
+            .and_then(|path| self.resolve_index_by_path(&path))
+            .or_else(|| self.state.visible_entries().first().map(|b| b.index));
+        self.window.mark_pane = None;
+        self.state.focussed = FocussedPane::Main;
+    }
+
+    /// Recomputes `state.entries` for the current root under the current sort
+    /// mode. Sort-mode keys only flip `state.sorting`, so this has to be called
+    /// afterwards or the cursor keeps navigating the previous order.
+    fn refresh_entries(&mut self) {
+        self.state.entries =
+            sorted_entries(&self.traversal.tree, self.state.root, self.state.sorting);
+    }
+
+    /// Finds the current `TreeIndex` of `path` by walking the live tree. Node removal
+    /// can reassign existing indices to unrelated entries, so anything that needs to
+    /// survive a removal (the selection, marks, the current root) is tracked by path
+    /// and re-resolved afterwards rather than trusted to keep the same `TreeIndex`.
+    fn resolve_index_by_path(&self, path: &Path) -> Option<TreeIndex> {
+        self.traversal
+            .tree
+            .node_indices()
+            .find(|&idx| path_of(&self.traversal.tree, idx) == path)
+    }
+
+    /// Removes `idx` and its descendants from the tree, subtracting its size from
+    /// every ancestor so the byte totals used by the bar visualizations stay accurate.
+    fn prune_entry(&mut self, idx: TreeIndex) {
+        let size = self.traversal.tree[idx].size;
+        let mut parent = self
+            .traversal
+            .tree
+            .neighbors_directed(idx, Direction::Incoming)
+            .next();
+        while let Some(p) = parent {
+            self.traversal.tree[p].size -= size;
+            parent = self
+                .traversal
+                .tree
+                .neighbors_directed(p, Direction::Incoming)
+                .next();
+        }
+
+        let mut dfs = Dfs::new(&self.traversal.tree, idx);
+        let mut to_remove = Vec::new();
+        while let Some(node) = dfs.next(&self.traversal.tree) {
+            to_remove.push(node);
+        }
+        // `remove_node` is a swap-remove: it moves the tree's last node into the
+        // freed slot, which invalidates that last node's index if it is still pending
+        // in this list.
+        // Removing from the highest index down means the node swapped into each
+        // freed slot always came from outside this batch, so the rest stay valid.
+        to_remove.sort_unstable_by(|a, b| b.cmp(a));
+        for node in to_remove {
+            self.traversal.tree.remove_node(node);
+        }
+    }
+
+    /// Re-walks `state.root` from disk and replaces its subtree in-place, leaving
+    /// the rest of the traversal tree untouched. Progress is rendered through the
+    /// same `terminal.render` callback used during the initial scan, and a large
+    /// rescan can be aborted with Ctrl-c: the callback polls a non-blocking stdin
+    /// reader on every tick, since raw mode delivers Ctrl-c as a plain key byte
+    /// that the main loop wouldn't otherwise see until the walk returns.
+    fn rescan<B>(&mut self, terminal: &mut Terminal<B>) -> Result<(), Error>
+    where
+        B: Backend,
+    {
+        let root_path = path_of(&self.traversal.tree, self.state.root);
+        let prior_size = self.traversal.tree[self.state.root].size;
+        let selected_path = self
+            .state
+            .selected
+            .map(|idx| path_of(&self.traversal.tree, idx));
+        let marked_paths: Vec<PathBuf> = self
+            .state
+            .marked
+            .marked
+            .iter()
+            .map(|&idx| path_of(&self.traversal.tree, idx))
+            .collect();
+
+        let mut children = Vec::new();
+        let mut dfs = Dfs::new(&self.traversal.tree, self.state.root);
+        dfs.next(&self.traversal.tree); // the root node itself is kept
+        while let Some(node) = dfs.next(&self.traversal.tree) {
+            children.push(node);
+        }
+        // As in `prune_entry`: `remove_node` swaps the tree's last node into the
+        // freed slot, so removing in descending index order is required - otherwise
+        // a node of this subtree can be swapped into an already-freed slot and is
+        // then never removed.
+        children.sort_unstable_by(|a, b| b.cmp(a));
+        for node in children {
+            self.traversal.tree.remove_node(node);
+        }
+        // Removing nodes above can reassign the index of any node that's still
+        // around, including the root itself - re-resolve it before touching it again.
+        let root = self
+            .resolve_index_by_path(&root_path)
+            .expect("root was just kept in place above");
+
+        let mut window = self.window.clone();
+        let options = self.walk_options.clone();
+        // `async_stdin` reads in the background so this never blocks the walk; checking
+        // it on every progress tick lets a Ctrl-c land immediately instead of sitting
+        // buffered until `rescan` returns and the main loop reads it as a `Key::Ctrl('c')`.
+        let mut interrupt = async_stdin().bytes();
+        let rescanned = Traversal::from_walk(options, vec![root_path], |_| {
+            if let Some(Ok(3)) = interrupt.next() {
+                return Err(failure::err_msg("rescan interrupted"));
+            }
+            terminal.render(&mut window, &*self, ()).map_err(Into::into)
+        });
+        self.window = window;
+        let rescanned = match rescanned {
+            Ok(rescanned) => rescanned,
+            Err(err) => {
+                // The old children are already gone: point the view at the re-resolved
+                // root and re-resolve or drop every index taken before the removal.
+                self.state.root = root;
+                self.state.entries = sorted_entries(&self.traversal.tree, root, self.state.sorting);
+                self.state.selected = None;
+                self.state.marked.marked = marked_paths
+                    .into_iter()
+                    .filter_map(|path| self.resolve_index_by_path(&path))
+                    .collect();
+                return Err(err);
+            }
+        };
+
+        let new_root = rescanned.root_index;
+        self.traversal.tree[root] = rescanned.tree[new_root].clone();
+        let mut stack = vec![(new_root, root)];
+        while let Some((other_idx, self_idx)) = stack.pop() {
+            for child in rescanned
+                .tree
+                .neighbors_directed(other_idx, Direction::Outgoing)
+            {
+                let grafted = self.traversal.tree.add_node(rescanned.tree[child].clone());
+                self.traversal.tree.add_edge(self_idx, grafted, ());
+                stack.push((child, grafted));
+            }
+        }
+
+        // `add_node`/`add_edge` above never invalidate existing indices, so `root`
+        // is still valid here; propagate the size delta to its ancestors the same
+        // way `prune_entry` does, so bars above it don't go stale.
+        let new_size = self.traversal.tree[root].size;
+        let delta = new_size as i64 - prior_size as i64;
+        if delta != 0 {
+            let mut parent = self
+                .traversal
+                .tree
+                .neighbors_directed(root, Direction::Incoming)
+                .next();
+            while let Some(p) = parent {
+                let size = &mut self.traversal.tree[p].size;
+                *size = (*size as i64 + delta) as u64;
+                parent = self
+                    .traversal
+                    .tree
+                    .neighbors_directed(p, Direction::Incoming)
+                    .next();
+            }
+        }
+
+        self.state.root = root;
+        self.state.entries = sorted_entries(&self.traversal.tree, root, self.state.sorting);
+        // Match the previous selection/marks by path rather than by the old
+        // TreeIndex values, which no longer mean anything after the subtree swap.
+        self.state.selected = selected_path
+            .and_then(|path| self.resolve_index_by_path(&path))
+            .or_else(|| self.state.visible_entries().first().map(|b| b.index));
+        self.state.marked.marked = marked_paths
+            .into_iter()
+            .filter_map(|path| self.resolve_index_by_path(&path))
+            .collect();
+        Ok(())
+    }
+
     fn exit_node(&mut self) {
         match self
             .traversal
@@ -169,7 +573,7 @@ impl TerminalApp {
                 self.state.root = parent_idx;
                 self.state.entries =
                     sorted_entries(&self.traversal.tree, parent_idx, self.state.sorting);
-                self.state.selected = self.state.entries.get(0).map(|b| b.index);
+                self.state.selected = self.state.visible_entries().first().map(|b| b.index);
             }
             None => self.state.message = Some("Top level reached".into()),
         }
@@ -178,12 +582,11 @@ impl TerminalApp {
     fn enter_node(&mut self) {
         if let Some(new_root) = self.state.selected {
             self.state.entries = sorted_entries(&self.traversal.tree, new_root, self.state.sorting);
-            match self.state.entries.get(0) {
-                Some(b) => {
-                    self.state.root = new_root;
-                    self.state.selected = Some(b.index);
-                }
-                None => self.state.message = Some("Entry is a file or an empty directory".into()),
+            if self.state.entries.is_empty() {
+                self.state.message = Some("Entry is a file or an empty directory".into());
+            } else {
+                self.state.root = new_root;
+                self.state.selected = self.state.visible_entries().first().map(|b| b.index);
             }
         }
     }
@@ -201,7 +604,7 @@ impl TerminalApp {
     }
 
     fn change_entry_selection(&mut self, direction: CursorDirection) {
-        let entries = sorted_entries(&self.traversal.tree, self.state.root, self.state.sorting);
+        let entries = self.state.visible_entries();
         let next_selected_pos = match self.state.selected {
             Some(ref selected) => entries
                 .iter()
@@ -211,6 +614,8 @@ impl TerminalApp {
                     CursorDirection::Down => idx.saturating_add(1),
                     CursorDirection::Up => idx.saturating_sub(1),
                     CursorDirection::PageUp => idx.saturating_sub(10),
+                    CursorDirection::Top => 0,
+                    CursorDirection::Bottom => usize::MAX,
                 })
                 .unwrap_or(0),
             None => 0,
@@ -234,6 +639,8 @@ impl TerminalApp {
         let mut display_options: DisplayOptions = options.clone().into();
         display_options.byte_vis = ByteVisualization::Bar;
         let mut window = ReactMainWindow::default();
+        let walk_options = options.clone();
+        let walk_options_for_scan = walk_options.clone();
 
         let traversal = Traversal::from_walk(options, input, move |traversal| {
             let state = AppState {
@@ -247,6 +654,7 @@ impl TerminalApp {
                 display: display_options,
                 state,
                 window: Default::default(),
+                walk_options: walk_options_for_scan.clone(),
             };
             terminal.render(&mut window, &app, ()).map_err(Into::into)
         })?;
@@ -267,6 +675,7 @@ impl TerminalApp {
             display: display_options,
             traversal,
             window: Default::default(),
+            walk_options,
         })
     }
-}
\ No newline at end of file
+}